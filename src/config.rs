@@ -19,15 +19,26 @@
 
 use std::error::Error;
 use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
 use std::net::SocketAddr;
 use std::num::{NonZeroUsize, ParseIntError};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::time::Duration;
 
 use humantime::parse_duration;
-use structopt::clap::ArgGroup;
+use serde::Deserialize;
+use structopt::clap::{ArgGroup, ArgMatches};
 use structopt::StructOpt;
 
+/// The ECN codepoint occupying the two least-significant bits of the IP
+/// ToS/Traffic-Class octet.
+const ECN_NOT_ECT: u8 = 0b00;
+const ECN_ECT_1: u8 = 0b01;
+const ECN_ECT_0: u8 = 0b10;
+const ECN_CE: u8 = 0b11;
+
 #[derive(Debug, Clone, Eq, PartialEq, StructOpt)]
 #[structopt(
     author = "Temirkhan Myrzamadi <gymmasssorla@gmail.com>",
@@ -36,28 +47,6 @@ use structopt::StructOpt;
     set_term_width = 80
 )]
 pub struct ArgsConfig {
-    /// A receiver of generated traffic, specified as an IP-address and a
-    /// port number, separated by a colon.
-    #[structopt(
-        short = "r",
-        long = "receiver",
-        takes_value = true,
-        value_name = "SOCKET-ADDRESS",
-        required = true
-    )]
-    pub receiver: SocketAddr,
-
-    /// A sender of generated traffic, specified as an IP-address and a
-    /// port number, separated by a colon.
-    #[structopt(
-        short = "s",
-        long = "sender",
-        takes_value = true,
-        value_name = "SOCKET-ADDRESS",
-        default_value = "0.0.0.0:0"
-    )]
-    pub sender: SocketAddr,
-
     /// A waiting time span before a test execution used to prevent a
     /// launch of an erroneous (unwanted) test.
     #[structopt(
@@ -92,17 +81,6 @@ pub struct ArgsConfig {
     )]
     pub display_periodicity: NonZeroUsize,
 
-    /// A timeout of sending every single packet. If a timeout is reached,
-    /// an error will be printed.
-    #[structopt(
-        long = "send-timeout",
-        takes_value = true,
-        value_name = "TIME-SPAN",
-        default_value = "10secs",
-        parse(try_from_str = "parse_duration")
-    )]
-    pub send_timeout: Duration,
-
     /// A name of a future test. This option lets produce the program
     /// beautiful output and doesn't make any sense on test performing.
     #[structopt(
@@ -114,14 +92,120 @@ pub struct ArgsConfig {
     )]
     pub test_name: String,
 
+    /// A YAML or TOML file (picked by its extension) describing a whole
+    /// test scenario, whose keys mirror the other command-line options.
+    /// Explicitly-provided command-line flags take priority over the ones
+    /// read from this file.
+    #[structopt(long = "config", takes_value = true, value_name = "FILENAME")]
+    pub config: Option<PathBuf>,
+
     #[structopt(flatten)]
     pub logging_config: LoggingConfig,
 
     #[structopt(flatten)]
-    pub stop_conditions_config: StopConditionsConfig,
+    pub network_config: NetworkConfig,
+
+    #[structopt(flatten)]
+    pub exit_config: StopConditionsConfig,
 
     #[structopt(flatten)]
     pub packet_config: PacketConfig,
+
+    #[structopt(flatten)]
+    pub rate_config: RateConfig,
+}
+
+#[derive(StructOpt, Debug, Clone, Eq, PartialEq)]
+pub struct RateConfig {
+    /// A target sending rate, in packets per second, enforced with a
+    /// token-bucket limiter. You cannot use this option and `--bandwidth`
+    /// together.
+    #[structopt(
+        long = "rate",
+        takes_value = true,
+        value_name = "PACKETS-PER-SECOND",
+        parse(try_from_str = "parse_non_zero_usize")
+    )]
+    pub rate: Option<NonZeroUsize>,
+
+    /// A target outbound bandwidth, in bits per second, enforced with a
+    /// token-bucket limiter. You cannot use this option and `--rate`
+    /// together.
+    #[structopt(
+        long = "bandwidth",
+        takes_value = true,
+        value_name = "BITS-PER-SECOND",
+        parse(try_from_str = "parse_non_zero_usize")
+    )]
+    pub bandwidth: Option<NonZeroUsize>,
+}
+
+#[derive(StructOpt, Debug, Clone, Eq, PartialEq)]
+pub struct NetworkConfig {
+    /// A receiver of generated traffic, specified as an IP-address and a
+    /// port number, separated by a colon. This option might be specified
+    /// several times to test multiple receivers at once. Required, unless
+    /// a `--config` file supplies its own `receivers` list.
+    #[structopt(
+        short = "r",
+        long = "receiver",
+        takes_value = true,
+        value_name = "SOCKET-ADDRESS",
+        number_of_values = 1,
+        multiple = true
+    )]
+    pub receivers: Vec<SocketAddr>,
+
+    /// A sender of generated traffic, specified as an IP-address and a
+    /// port number, separated by a colon.
+    #[structopt(
+        short = "s",
+        long = "sender",
+        takes_value = true,
+        value_name = "SOCKET-ADDRESS",
+        default_value = "0.0.0.0:0"
+    )]
+    pub sender: SocketAddr,
+
+    /// Enable the SO_BROADCAST socket option, allowing the generated
+    /// traffic to be sent to a broadcast address.
+    #[structopt(long = "broadcast", takes_value = false)]
+    pub broadcast: bool,
+
+    /// A timeout of sending every single packet. If a timeout is reached,
+    /// an error will be printed.
+    #[structopt(
+        long = "send-timeout",
+        takes_value = true,
+        value_name = "TIME-SPAN",
+        default_value = "10secs",
+        parse(try_from_str = "parse_duration")
+    )]
+    pub send_timeout: Duration,
+
+    /// A count of packets sent per one system call. Increasing this value
+    /// reduces syscall overhead at the cost of coarser pacing.
+    #[structopt(
+        long = "packets-per-syscall",
+        takes_value = true,
+        value_name = "POSITIVE-INTEGER",
+        default_value = "1",
+        parse(try_from_str = "parse_non_zero_usize")
+    )]
+    pub packets_per_syscall: NonZeroUsize,
+
+    /// An IP Type-of-Service/Traffic-Class octet applied to every generated
+    /// socket, letting you stress a target's QoS/DiffServ handling. Accepts
+    /// either a raw 0-255 value or a DSCP mnemonic (e.g. `EF`, `CS5`,
+    /// `AF41`), optionally followed by `:` and an ECN codepoint (`NotECT`,
+    /// `ECT1`, `ECT0` or `CE`, defaulting to `NotECT`).
+    #[structopt(
+        long = "ip-tos",
+        takes_value = true,
+        value_name = "TOS-OCTET",
+        parse(try_from_str = "parse_ip_tos")
+    )]
+    pub ip_tos: Option<u8>,
 }
 
 #[derive(StructOpt, Debug, Clone, Eq, PartialEq)]
@@ -139,8 +223,55 @@ pub struct LoggingConfig {
     /// Enable the debugging mode
     #[structopt(short = "d", long = "debug", takes_value = false)]
     pub debug: bool,
+
+    /// A format of the produced log records. `text` prints human-oriented
+    /// colored messages, while `json` prints one newline-delimited JSON
+    /// object per significant event, suitable for feeding into dashboards.
+    #[structopt(
+        long = "log-format",
+        takes_value = true,
+        value_name = "FORMAT",
+        default_value = "text",
+        parse(try_from_str = "parse_log_format")
+    )]
+    pub log_format: LogFormat,
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+pub fn parse_log_format(value: &str) -> Result<LogFormat, LogFormatError> {
+    match value {
+        "text" => Ok(LogFormat::Text),
+        "json" => Ok(LogFormat::Json),
+        other => Err(LogFormatError::Unknown(other.to_owned())),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogFormatError {
+    Unknown(String),
+}
+
+impl Display for LogFormatError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            LogFormatError::Unknown(value) => {
+                write!(
+                    fmt,
+                    "Unknown log format '{}', expected 'text' or 'json'",
+                    value
+                )
+            }
+        }
+    }
+}
+
+impl Error for LogFormatError {}
+
 #[derive(StructOpt, Debug, Clone, Eq, PartialEq)]
 pub struct StopConditionsConfig {
     /// A count of packets for sending. When this limit is reached, then
@@ -189,19 +320,91 @@ pub struct PacketConfig {
         value_name = "FILENAME"
     )]
     pub send_file: Option<PathBuf>,
+
+    /// A file describing a packet template built from literal bytes, fixed-
+    /// width integer fields, an auto-filling length field, and random(N)
+    /// spans. You cannot use this option with `--send-file` or
+    /// `--packet-length` together.
+    #[structopt(long = "packet-template", takes_value = true, value_name = "FILENAME")]
+    pub packet_template: Option<PathBuf>,
 }
 
 impl ArgsConfig {
     pub fn setup() -> ArgsConfig {
         let matches = ArgsConfig::clap()
-            .group(ArgGroup::with_name("message").args(&["send_file", "packet_length"]))
+            .group(ArgGroup::with_name("message").args(&[
+                "send_file",
+                "packet_length",
+                "packet_template",
+            ]))
+            .group(ArgGroup::with_name("rate_limit").args(&["rate", "bandwidth"]))
             .get_matches();
 
         let mut args_config = ArgsConfig::from_clap(&matches);
 
-        // If an user hasn't specified a file, then set the default packet
-        // length
-        if !matches.is_present("send_file") {
+        if let Some(path) = args_config.config.clone() {
+            match FileConfig::read(&path) {
+                Ok(file_config) => merge_file_config(&mut args_config, &matches, &file_config),
+                Err(error) => {
+                    eprintln!(
+                        "Unable to load the config file {path} >>> {error}",
+                        path = path.display(),
+                        error = error
+                    );
+                    process::exit(1);
+                }
+            }
+        }
+
+        // `--receiver` isn't marked `required` at the `clap` level anymore
+        // because a `--config` file is now allowed to supply the receivers
+        // instead, so the requirement has to be enforced here, once both
+        // sources have been merged.
+        if args_config.network_config.receivers.is_empty() {
+            eprintln!(
+                "error: The following required arguments were not provided:\n    \
+                 --receiver <SOCKET-ADDRESS>...\n\n\
+                 (pass it on the command line, or list `receivers` in a --config file)\n\n\
+                 For more information try --help"
+            );
+            process::exit(1);
+        }
+
+        // The command line enforces these exclusivity rules through `clap`'s
+        // `ArgGroup`s, but values merged in from a `--config` file bypass
+        // `clap` entirely, so they have to be re-checked by hand here.
+        exit_if_more_than_one(
+            "rate_limit",
+            &[
+                ("--rate", args_config.rate_config.rate.is_some()),
+                ("--bandwidth", args_config.rate_config.bandwidth.is_some()),
+            ],
+        );
+        exit_if_more_than_one(
+            "message",
+            &[
+                (
+                    "--send-file",
+                    args_config.packet_config.send_file.is_some(),
+                ),
+                (
+                    "--packet-length",
+                    args_config.packet_config.packet_length.is_some(),
+                ),
+                (
+                    "--packet-template",
+                    args_config.packet_config.packet_template.is_some(),
+                ),
+            ],
+        );
+
+        // If an user hasn't specified a file or a packet template (neither
+        // on the command line nor inside the config file), then set the
+        // default packet length
+        if args_config.packet_config.send_file.is_none()
+            && args_config.packet_config.packet_template.is_none()
+            && args_config.packet_config.packet_length.is_none()
+        {
             args_config.packet_config.packet_length =
                 Some(unsafe { NonZeroUsize::new_unchecked(65000) });
         }
@@ -210,6 +413,252 @@ impl ArgsConfig {
     }
 }
 
+/// A plain, loosely-typed mirror of `ArgsConfig` (and the structs it
+/// flattens) that a YAML or TOML config file is deserialized into. Every
+/// field is optional: only the ones a user actually specified are merged
+/// into the `ArgsConfig` built from the command line, which always wins on
+/// conflicts.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct FileConfig {
+    wait: Option<String>,
+    send_periodicity: Option<String>,
+    display_periodicity: Option<String>,
+    test_name: Option<String>,
+    receivers: Option<Vec<SocketAddr>>,
+    sender: Option<SocketAddr>,
+    broadcast: Option<bool>,
+    send_timeout: Option<String>,
+    packets_per_syscall: Option<String>,
+    ip_tos: Option<String>,
+    rate: Option<String>,
+    bandwidth: Option<String>,
+    packets_count: Option<String>,
+    test_duration: Option<String>,
+    packet_length: Option<String>,
+    send_file: Option<PathBuf>,
+    packet_template: Option<PathBuf>,
+    output: Option<PathBuf>,
+    debug: Option<bool>,
+    log_format: Option<String>,
+}
+
+impl FileConfig {
+    fn read(path: &Path) -> Result<FileConfig, ConfigFileError> {
+        let content = fs::read_to_string(path).map_err(ConfigFileError::ReadFailed)?;
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => toml::from_str(&content).map_err(ConfigFileError::InvalidToml),
+            _ => serde_yaml::from_str(&content).map_err(ConfigFileError::InvalidYaml),
+        }
+    }
+}
+
+// Overwrites every `args_config` field whose command-line flag wasn't
+// explicitly provided (`clap`'s `name` identifiers match the struct field
+// names regardless of flattening) with the corresponding value from
+// `file_config`, reusing the very same validators the command line uses.
+// `occurrences_of` (rather than `is_present`) is what tells an unsupplied,
+// `default_value`d flag apart from one the user actually typed.
+fn merge_file_config(args_config: &mut ArgsConfig, matches: &ArgMatches, file_config: &FileConfig) {
+    macro_rules! merge_parsed {
+        ($flag:expr, $field:expr, $raw:expr, $parse:expr) => {
+            if matches.occurrences_of($flag) == 0 {
+                if let Some(ref raw) = $raw {
+                    match $parse(raw) {
+                        Ok(value) => $field = value,
+                        Err(error) => exit_with_parse_error($flag, error),
+                    }
+                }
+            }
+        };
+    }
+
+    macro_rules! merge_plain {
+        ($flag:expr, $field:expr, $raw:expr) => {
+            if matches.occurrences_of($flag) == 0 {
+                if let Some(ref raw) = $raw {
+                    $field = raw.clone();
+                }
+            }
+        };
+    }
+
+    macro_rules! merge_plain_opt {
+        ($flag:expr, $field:expr, $raw:expr) => {
+            if matches.occurrences_of($flag) == 0 {
+                if let Some(ref raw) = $raw {
+                    $field = Some(raw.clone());
+                }
+            }
+        };
+    }
+
+    merge_parsed!("wait", args_config.wait, file_config.wait, parse_duration);
+    merge_parsed!(
+        "send_periodicity",
+        args_config.send_periodicity,
+        file_config.send_periodicity,
+        parse_duration
+    );
+    merge_parsed!(
+        "display_periodicity",
+        args_config.display_periodicity,
+        file_config.display_periodicity,
+        parse_non_zero_usize
+    );
+    merge_plain!("test_name", args_config.test_name, file_config.test_name);
+
+    merge_plain!(
+        "receivers",
+        args_config.network_config.receivers,
+        file_config.receivers
+    );
+    merge_plain!(
+        "sender",
+        args_config.network_config.sender,
+        file_config.sender
+    );
+    merge_plain!(
+        "broadcast",
+        args_config.network_config.broadcast,
+        file_config.broadcast
+    );
+    merge_parsed!(
+        "send_timeout",
+        args_config.network_config.send_timeout,
+        file_config.send_timeout,
+        parse_duration
+    );
+    merge_parsed!(
+        "packets_per_syscall",
+        args_config.network_config.packets_per_syscall,
+        file_config.packets_per_syscall,
+        parse_non_zero_usize
+    );
+    if matches.occurrences_of("ip_tos") == 0 {
+        if let Some(ref raw) = file_config.ip_tos {
+            match parse_ip_tos(raw) {
+                Ok(value) => args_config.network_config.ip_tos = Some(value),
+                Err(error) => exit_with_parse_error("ip-tos", error),
+            }
+        }
+    }
+
+    if matches.occurrences_of("rate") == 0 {
+        if let Some(ref raw) = file_config.rate {
+            match parse_non_zero_usize(raw) {
+                Ok(value) => args_config.rate_config.rate = Some(value),
+                Err(error) => exit_with_parse_error("rate", error),
+            }
+        }
+    }
+    if matches.occurrences_of("bandwidth") == 0 {
+        if let Some(ref raw) = file_config.bandwidth {
+            match parse_non_zero_usize(raw) {
+                Ok(value) => args_config.rate_config.bandwidth = Some(value),
+                Err(error) => exit_with_parse_error("bandwidth", error),
+            }
+        }
+    }
+
+    merge_parsed!(
+        "packets_count",
+        args_config.exit_config.packets_count,
+        file_config.packets_count,
+        parse_non_zero_usize
+    );
+    merge_parsed!(
+        "test_duration",
+        args_config.exit_config.test_duration,
+        file_config.test_duration,
+        parse_duration
+    );
+
+    if matches.occurrences_of("packet_length") == 0 {
+        if let Some(ref raw) = file_config.packet_length {
+            match parse_non_zero_usize(raw) {
+                Ok(value) => args_config.packet_config.packet_length = Some(value),
+                Err(error) => exit_with_parse_error("packet-length", error),
+            }
+        }
+    }
+    merge_plain_opt!(
+        "send_file",
+        args_config.packet_config.send_file,
+        file_config.send_file
+    );
+    merge_plain_opt!(
+        "packet_template",
+        args_config.packet_config.packet_template,
+        file_config.packet_template
+    );
+
+    merge_plain_opt!(
+        "output",
+        args_config.logging_config.output,
+        file_config.output
+    );
+    merge_plain!("debug", args_config.logging_config.debug, file_config.debug);
+    if matches.occurrences_of("log_format") == 0 {
+        if let Some(ref raw) = file_config.log_format {
+            match parse_log_format(raw) {
+                Ok(value) => args_config.logging_config.log_format = value,
+                Err(error) => exit_with_parse_error("log-format", error),
+            }
+        }
+    }
+}
+
+fn exit_with_parse_error<E: Display>(option: &str, error: E) -> ! {
+    eprintln!(
+        "Invalid value for '{option}' in the config file >>> {error}",
+        option = option,
+        error = error
+    );
+    process::exit(1);
+}
+
+// Mirrors `clap`'s own `ArgGroup` conflict check for options whose final
+// value may have come from a merged `--config` file rather than the command
+// line, where `clap` never gets a chance to enforce it.
+fn exit_if_more_than_one(group: &str, options: &[(&str, bool)]) {
+    let present: Vec<&str> = options
+        .iter()
+        .filter(|(_, is_present)| *is_present)
+        .map(|(name, _)| *name)
+        .collect();
+
+    if present.len() > 1 {
+        eprintln!(
+            "error: The argument(s) {args} cannot be used together (the '{group}' options are \
+             mutually exclusive)",
+            args = present.join(", "),
+            group = group
+        );
+        process::exit(1);
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigFileError {
+    ReadFailed(io::Error),
+    InvalidYaml(serde_yaml::Error),
+    InvalidToml(toml::de::Error),
+}
+
+impl Display for ConfigFileError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            ConfigFileError::ReadFailed(error) => write!(fmt, "{}", error),
+            ConfigFileError::InvalidYaml(error) => write!(fmt, "{}", error),
+            ConfigFileError::InvalidToml(error) => write!(fmt, "{}", error),
+        }
+    }
+}
+
+impl Error for ConfigFileError {}
+
 pub fn parse_non_zero_usize(number: &str) -> Result<NonZeroUsize, NonZeroUsizeError> {
     let number: usize = number
         .parse()
@@ -235,6 +684,102 @@ impl Display for NonZeroUsizeError {
 
 impl Error for NonZeroUsizeError {}
 
+/// Parses an IP ToS/Traffic-Class octet, accepting either a raw 0-255 value
+/// or a `DSCP[:ECN]` mnemonic pair (see `--ip-tos` for the accepted names).
+pub fn parse_ip_tos(value: &str) -> Result<u8, IpTosError> {
+    let mut parts = value.splitn(2, ':');
+    let dscp_part = parts.next().unwrap();
+    let ecn_part = parts.next();
+
+    // A bare numeric value is taken as the whole octet, DSCP and ECN bits
+    // included, so that users can still specify the exact byte if they want
+    // to.
+    if ecn_part.is_none() {
+        if let Ok(octet) = dscp_part.parse::<u16>() {
+            return if octet <= 0xff {
+                Ok(octet as u8)
+            } else {
+                Err(IpTosError::OutOfRange)
+            };
+        }
+    }
+
+    let dscp = parse_dscp(dscp_part)?;
+    let ecn = match ecn_part {
+        Some(mnemonic) => parse_ecn(mnemonic)?,
+        None => ECN_NOT_ECT,
+    };
+
+    Ok((dscp << 2) | ecn)
+}
+
+fn parse_dscp(mnemonic: &str) -> Result<u8, IpTosError> {
+    match mnemonic.to_ascii_uppercase().as_str() {
+        "CS0" | "BE" | "DEFAULT" => Ok(0),
+        "CS1" => Ok(8),
+        "AF11" => Ok(10),
+        "AF12" => Ok(12),
+        "AF13" => Ok(14),
+        "CS2" => Ok(16),
+        "AF21" => Ok(18),
+        "AF22" => Ok(20),
+        "AF23" => Ok(22),
+        "CS3" => Ok(24),
+        "AF31" => Ok(26),
+        "AF32" => Ok(28),
+        "AF33" => Ok(30),
+        "CS4" => Ok(32),
+        "AF41" => Ok(34),
+        "AF42" => Ok(36),
+        "AF43" => Ok(38),
+        "CS5" => Ok(40),
+        "EF" => Ok(46),
+        "CS6" => Ok(48),
+        "CS7" => Ok(56),
+        other => other
+            .parse::<u8>()
+            .map_err(|_| IpTosError::UnknownDscp(mnemonic.to_owned()))
+            .and_then(|dscp| {
+                if dscp <= 0b0011_1111 {
+                    Ok(dscp)
+                } else {
+                    Err(IpTosError::OutOfRange)
+                }
+            }),
+    }
+}
+
+fn parse_ecn(mnemonic: &str) -> Result<u8, IpTosError> {
+    match mnemonic.to_ascii_uppercase().as_str() {
+        "NOTECT" | "NOT-ECT" => Ok(ECN_NOT_ECT),
+        "ECT1" | "ECT(1)" => Ok(ECN_ECT_1),
+        "ECT0" | "ECT(0)" => Ok(ECN_ECT_0),
+        "CE" => Ok(ECN_CE),
+        other => Err(IpTosError::UnknownEcn(other.to_owned())),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpTosError {
+    UnknownDscp(String),
+    UnknownEcn(String),
+    OutOfRange,
+}
+
+impl Display for IpTosError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            IpTosError::UnknownDscp(mnemonic) => {
+                write!(fmt, "Unknown DSCP mnemonic '{}'", mnemonic)
+            }
+            IpTosError::UnknownEcn(mnemonic) => write!(fmt, "Unknown ECN mnemonic '{}'", mnemonic),
+            IpTosError::OutOfRange => write!(fmt, "The value doesn't fit into a single octet"),
+        }
+    }
+}
+
+impl Error for IpTosError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -282,4 +827,94 @@ mod tests {
         // Check that the zero value is not allowed
         assert_eq!(parse_non_zero_usize("0"), Err(NonZeroUsizeError::ZeroValue));
     }
+
+    #[test]
+    fn parses_valid_ip_tos() {
+        // A raw octet is taken as-is
+        assert_eq!(parse_ip_tos("184"), Ok(184));
+
+        // DSCP mnemonics default to the Not-ECT codepoint
+        assert_eq!(parse_ip_tos("EF"), Ok(46 << 2));
+        assert_eq!(parse_ip_tos("cs5"), Ok(40 << 2));
+        assert_eq!(parse_ip_tos("AF41"), Ok((34 << 2) | 0b00));
+
+        // An explicit ECN codepoint is composed into the low two bits
+        assert_eq!(parse_ip_tos("EF:CE"), Ok((46 << 2) | 0b11));
+        assert_eq!(parse_ip_tos("af41:ect0"), Ok((34 << 2) | 0b10));
+    }
+
+    #[test]
+    fn parses_invalid_ip_tos() {
+        assert_eq!(parse_ip_tos("256"), Err(IpTosError::OutOfRange));
+        assert!(parse_ip_tos("NOT-A-DSCP").is_err());
+        assert!(parse_ip_tos("EF:NOT-AN-ECN").is_err());
+    }
+
+    #[test]
+    fn parses_valid_log_format() {
+        assert_eq!(parse_log_format("text"), Ok(LogFormat::Text));
+        assert_eq!(parse_log_format("json"), Ok(LogFormat::Json));
+    }
+
+    #[test]
+    fn parses_invalid_log_format() {
+        assert!(parse_log_format("xml").is_err());
+    }
+
+    #[test]
+    fn reads_yaml_config_file() {
+        use std::io::Write;
+
+        let mut temp = tempfile::Builder::new()
+            .suffix(".yaml")
+            .tempfile()
+            .expect("Cannot create a temporary file");
+        temp.write_all(b"receivers:\n  - 127.0.0.1:8080\nrate: \"500\"\ntest-name: my-test\n")
+            .unwrap();
+
+        let file_config = FileConfig::read(temp.path()).expect("Cannot read the config file");
+        assert_eq!(file_config.test_name, Some("my-test".to_owned()));
+        assert_eq!(file_config.rate, Some("500".to_owned()));
+        assert_eq!(
+            file_config.receivers,
+            Some(vec!["127.0.0.1:8080".parse().unwrap()])
+        );
+    }
+
+    #[test]
+    fn reads_toml_config_file() {
+        use std::io::Write;
+
+        let mut temp = tempfile::Builder::new()
+            .suffix(".toml")
+            .tempfile()
+            .expect("Cannot create a temporary file");
+        temp.write_all(b"test-name = \"my-test\"\nbroadcast = true\n")
+            .unwrap();
+
+        let file_config = FileConfig::read(temp.path()).expect("Cannot read the config file");
+        assert_eq!(file_config.test_name, Some("my-test".to_owned()));
+        assert_eq!(file_config.broadcast, Some(true));
+    }
+
+    #[test]
+    fn merges_defaulted_field_from_config_file() {
+        // None of these flags are passed, so `send_timeout` only has its
+        // `default_value`, which `clap` still reports as "present" -- the
+        // merge has to tell that apart from an explicitly-passed flag.
+        let matches = ArgsConfig::clap().get_matches_from(&["anevicon", "-r", "127.0.0.1:8080"]);
+        let mut args_config = ArgsConfig::from_clap(&matches);
+        assert_eq!(args_config.network_config.send_timeout, Duration::from_secs(10));
+
+        let file_config = FileConfig {
+            send_timeout: Some("30secs".to_owned()),
+            ..Default::default()
+        };
+        merge_file_config(&mut args_config, &matches, &file_config);
+
+        assert_eq!(
+            args_config.network_config.send_timeout,
+            Duration::from_secs(30)
+        );
+    }
 }
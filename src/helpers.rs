@@ -21,23 +21,146 @@ use std::error::Error;
 use std::fmt::{self, Display, Formatter};
 use std::fs;
 use std::io;
+use std::net::SocketAddr;
 use std::num::NonZeroUsize;
 use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use anevicon_core::TestSummary;
+use colored::{ColoredString, Colorize as _};
 use rand::{thread_rng, RngCore};
+use serde_json::json;
 
 use super::config::ArgsConfig;
 
-pub fn construct_packet(args_config: &ArgsConfig) -> Result<Vec<u8>, ReadPacketError> {
-    // If our user specified a file, then use file content as a packet.
-    // Otherwise, generate a random set of bytes to use as a packet.
-    if let Some(ref filename) = args_config.file {
+/// Colorizes an arbitrary displayable value as cyan, used to highlight
+/// receivers in the human-readable log output.
+pub fn cyan<T: Display>(value: T) -> ColoredString {
+    value.to_string().cyan()
+}
+
+/// Wraps a `TestSummary` reference so it can be formatted for humans with
+/// `Display`, as opposed to the structured JSON representation produced by
+/// `json_summary_event`.
+pub struct SummaryWrapper<'a>(pub &'a TestSummary);
+
+impl<'a> Display for SummaryWrapper<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{packets_sent} of {packets_expected} packets sent ({bytes_sent} bytes) in \
+             {time_passed:?}",
+            packets_sent = self.0.packets_sent(),
+            packets_expected = self.0.packets_expected(),
+            bytes_sent = self.0.bytes_sent(),
+            time_passed = self.0.time_passed(),
+        )
+    }
+}
+
+/// Serializes a significant event carrying a `TestSummary` snapshot (a
+/// periodic summary, the expired-time notice, or the final completion
+/// notice) into a single JSON line.
+pub fn json_summary_event(receiver: SocketAddr, event: &str, summary: &TestSummary) -> String {
+    json!({
+        "ts": current_millis(),
+        "receiver": receiver.to_string(),
+        "event": event,
+        "packets_sent": summary.packets_sent(),
+        "packets_expected": summary.packets_expected(),
+        "bytes_sent": summary.bytes_sent(),
+        "time_passed_ms": summary.time_passed().as_millis(),
+    })
+    .to_string()
+}
+
+/// Serializes a significant event that only carries a free-form message (a
+/// socket initialization notice, a resend notice, or a send error) into a
+/// single JSON line.
+pub fn json_message_event<M: Display>(receiver: SocketAddr, event: &str, message: M) -> String {
+    json!({
+        "ts": current_millis(),
+        "receiver": receiver.to_string(),
+        "event": event,
+        "message": message.to_string(),
+    })
+    .to_string()
+}
+
+fn current_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The system clock is set before the UNIX epoch")
+        .as_millis()
+}
+
+/// Either a fixed payload shared by every sent packet, or a template that
+/// renders a fresh, independently randomized payload on every call.
+pub enum PacketSource {
+    Fixed(Vec<u8>),
+    Templated(PacketTemplate),
+}
+
+impl PacketSource {
+    /// The length, in bytes, of every packet this source produces.
+    pub fn len(&self) -> usize {
+        match self {
+            PacketSource::Fixed(bytes) => bytes.len(),
+            PacketSource::Templated(template) => template.len(),
+        }
+    }
+
+    /// Writes one concrete packet into `buf`. For a `Fixed` source this
+    /// just copies the shared payload; for a `Templated` one it renders a
+    /// fresh, independently randomized packet.
+    pub fn render<R: RngCore>(&self, buf: &mut [u8], rng: &mut R) {
+        match self {
+            PacketSource::Fixed(bytes) => buf.copy_from_slice(bytes),
+            PacketSource::Templated(template) => template.render(buf, rng),
+        }
+    }
+}
+
+pub fn construct_packet(args_config: &ArgsConfig) -> Result<PacketSource, ConstructPacketError> {
+    let packet_config = &args_config.packet_config;
+
+    // If our user specified a file, then use its content as a packet. If
+    // they specified a packet template, render from that instead. Otherwise,
+    // generate a random set of bytes to use as a packet.
+    if let Some(ref filename) = packet_config.send_file {
         read_packet(filename)
+            .map(PacketSource::Fixed)
+            .map_err(ConstructPacketError::ReadPacket)
+    } else if let Some(ref filename) = packet_config.packet_template {
+        PacketTemplate::load(filename)
+            .map(PacketSource::Templated)
+            .map_err(ConstructPacketError::Template)
     } else {
-        Ok(random_packet(args_config.length))
+        Ok(PacketSource::Fixed(random_packet(
+            packet_config
+                .packet_length
+                .expect("Neither a file, a packet template, nor a packet length was specified"),
+        )))
+    }
+}
+
+#[derive(Debug)]
+pub enum ConstructPacketError {
+    ReadPacket(ReadPacketError),
+    Template(TemplateError),
+}
+
+impl Display for ConstructPacketError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            ConstructPacketError::ReadPacket(error) => write!(fmt, "{}", error),
+            ConstructPacketError::Template(error) => write!(fmt, "{}", error),
+        }
     }
 }
 
+impl Error for ConstructPacketError {}
+
 pub fn read_packet<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, ReadPacketError> {
     let content = fs::read(path).map_err(|error| ReadPacketError::ReadFailed(error))?;
 
@@ -77,6 +200,286 @@ pub fn random_packet(length: NonZeroUsize) -> Vec<u8> {
     buffer
 }
 
+/// A parsed packet template, describing a byte layout built from literal
+/// bytes, fixed-width integer fields, an auto-filling length field, and
+/// `random(N)` spans. Call `render` to write one concrete, freshly
+/// randomized packet for every send.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PacketTemplate {
+    fields: Vec<Field>,
+    total_len: usize,
+}
+
+impl PacketTemplate {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PacketTemplate, TemplateError> {
+        let content = fs::read_to_string(path).map_err(TemplateError::ReadFailed)?;
+        PacketTemplate::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<PacketTemplate, TemplateError> {
+        let mut fields = Vec::new();
+
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let field = Field::parse(line)
+                .map_err(|error| TemplateError::InvalidLine(number + 1, error))?;
+            fields.push(field);
+        }
+
+        let total_len = fields.iter().map(Field::len).sum();
+
+        // Whether the total packet size fits a length field's width is only
+        // known once every field has been parsed, so this can't be checked
+        // until now.
+        for field in &fields {
+            let width = match field {
+                Field::Length(width, _) => *width,
+                _ => continue,
+            };
+
+            if total_len as u64 > width.max_value() {
+                return Err(TemplateError::LengthOutOfRange(total_len, width.bytes()));
+            }
+        }
+
+        Ok(PacketTemplate { fields, total_len })
+    }
+
+    /// The total length, in bytes, of every packet this template renders.
+    pub fn len(&self) -> usize {
+        self.total_len
+    }
+
+    /// Writes one concrete packet into `buf`, which must be exactly
+    /// `self.len()` bytes long. Every `random(N)` span is re-randomized and
+    /// every length field is recomputed on each call.
+    pub fn render<R: RngCore>(&self, buf: &mut [u8], rng: &mut R) {
+        assert_eq!(
+            buf.len(),
+            self.total_len,
+            "The destination buffer doesn't match the template's length"
+        );
+
+        let mut offset = 0;
+        for field in &self.fields {
+            let len = field.len();
+            field.render(&mut buf[offset..offset + len], self.total_len, rng);
+            offset += len;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IntWidth {
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl IntWidth {
+    fn bytes(self) -> usize {
+        match self {
+            IntWidth::U8 => 1,
+            IntWidth::U16 => 2,
+            IntWidth::U32 => 4,
+            IntWidth::U64 => 8,
+        }
+    }
+
+    fn max_value(self) -> u64 {
+        match self {
+            IntWidth::U8 => u8::MAX as u64,
+            IntWidth::U16 => u16::MAX as u64,
+            IntWidth::U32 => u32::MAX as u64,
+            IntWidth::U64 => u64::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endian {
+    Big,
+    Little,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    Literal(Vec<u8>),
+    Integer(IntWidth, Endian, u64),
+    Length(IntWidth, Endian),
+    Random(usize),
+}
+
+impl Field {
+    fn len(&self) -> usize {
+        match self {
+            Field::Literal(bytes) => bytes.len(),
+            Field::Integer(width, _, _) | Field::Length(width, _) => width.bytes(),
+            Field::Random(size) => *size,
+        }
+    }
+
+    fn render<R: RngCore>(&self, slot: &mut [u8], total_len: usize, rng: &mut R) {
+        match self {
+            Field::Literal(bytes) => slot.copy_from_slice(bytes),
+            Field::Integer(width, endian, value) => write_integer(slot, *width, *endian, *value),
+            Field::Length(width, endian) => write_integer(slot, *width, *endian, total_len as u64),
+            Field::Random(_) => rng.fill_bytes(slot),
+        }
+    }
+
+    fn parse(line: &str) -> Result<Field, FieldParseError> {
+        if let Some(size) = line
+            .strip_prefix("random(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            return size
+                .parse()
+                .map(Field::Random)
+                .map_err(|_| FieldParseError::InvalidNumber(size.to_owned()));
+        }
+
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let keyword = parts.next().unwrap();
+        let rest = parts.next().unwrap_or("").trim();
+
+        match keyword {
+            "literal" => parse_hex(rest).map(Field::Literal),
+            "ascii" => Ok(Field::Literal(unquote(rest).into_bytes())),
+            _ => {
+                if let Some(width_endian) = keyword.strip_prefix("length-") {
+                    let (width, endian) = parse_int_keyword(width_endian)
+                        .ok_or_else(|| FieldParseError::UnknownKeyword(keyword.to_owned()))?;
+                    Ok(Field::Length(width, endian))
+                } else if let Some((width, endian)) = parse_int_keyword(keyword) {
+                    let value: u64 = rest
+                        .parse()
+                        .map_err(|_| FieldParseError::InvalidNumber(rest.to_owned()))?;
+                    if value > width.max_value() {
+                        return Err(FieldParseError::ValueOutOfRange(value, width.bytes()));
+                    }
+                    Ok(Field::Integer(width, endian, value))
+                } else {
+                    Err(FieldParseError::UnknownKeyword(keyword.to_owned()))
+                }
+            }
+        }
+    }
+}
+
+// Recognizes keywords like `u16be`/`u32le` as an (IntWidth, Endian) pair.
+fn parse_int_keyword(keyword: &str) -> Option<(IntWidth, Endian)> {
+    let (width, endian) = if let Some(width) = keyword.strip_suffix("be") {
+        (width, Endian::Big)
+    } else if let Some(width) = keyword.strip_suffix("le") {
+        (width, Endian::Little)
+    } else {
+        return None;
+    };
+
+    let width = match width {
+        "u8" => IntWidth::U8,
+        "u16" => IntWidth::U16,
+        "u32" => IntWidth::U32,
+        "u64" => IntWidth::U64,
+        _ => return None,
+    };
+
+    Some((width, endian))
+}
+
+fn write_integer(slot: &mut [u8], width: IntWidth, endian: Endian, value: u64) {
+    match endian {
+        Endian::Big => slot.copy_from_slice(&value.to_be_bytes()[8 - width.bytes()..]),
+        Endian::Little => slot.copy_from_slice(&value.to_le_bytes()[..width.bytes()]),
+    }
+}
+
+fn parse_hex(value: &str) -> Result<Vec<u8>, FieldParseError> {
+    let cleaned: String = value.chars().filter(|byte| !byte.is_whitespace()).collect();
+
+    if cleaned.len() % 2 != 0 {
+        return Err(FieldParseError::InvalidHex(cleaned));
+    }
+
+    (0..cleaned.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&cleaned[i..i + 2], 16)
+                .map_err(|_| FieldParseError::InvalidHex(cleaned.clone()))
+        })
+        .collect()
+}
+
+fn unquote(value: &str) -> String {
+    value
+        .trim_matches('"')
+        .replace("\\r", "\r")
+        .replace("\\n", "\n")
+        .replace("\\t", "\t")
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    ReadFailed(io::Error),
+    InvalidLine(usize, FieldParseError),
+    LengthOutOfRange(usize, usize),
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            TemplateError::ReadFailed(error) => write!(fmt, "{}", error),
+            TemplateError::InvalidLine(number, error) => {
+                write!(fmt, "Line {} >>> {}", number, error)
+            }
+            TemplateError::LengthOutOfRange(total_len, width) => write!(
+                fmt,
+                "The packet is {} bytes long, which doesn't fit in a {}-byte length field",
+                total_len, width
+            ),
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldParseError {
+    UnknownKeyword(String),
+    InvalidHex(String),
+    InvalidNumber(String),
+    ValueOutOfRange(u64, usize),
+}
+
+impl Display for FieldParseError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            FieldParseError::UnknownKeyword(keyword) => {
+                write!(fmt, "Unknown field keyword '{}'", keyword)
+            }
+            FieldParseError::InvalidHex(value) => {
+                write!(fmt, "Invalid hex literal '{}'", value)
+            }
+            FieldParseError::InvalidNumber(value) => {
+                write!(fmt, "Invalid number '{}'", value)
+            }
+            FieldParseError::ValueOutOfRange(value, width) => write!(
+                fmt,
+                "Value {} doesn't fit in a {}-byte integer field",
+                value, width
+            ),
+        }
+    }
+}
+
+impl Error for FieldParseError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -117,4 +520,61 @@ mod tests {
         assert_eq!(buffer.len(), length.get());
         assert!(buffer.capacity() >= length.get());
     }
+
+    #[test]
+    fn parses_and_renders_template() {
+        let template =
+            PacketTemplate::parse("literal deadbeef\nu16be 1234\nu32le 1\nrandom(4)\nlength-u16be")
+                .expect("Must parse a valid template");
+
+        assert_eq!(template.len(), 4 + 2 + 4 + 4 + 2);
+
+        let mut buffer = vec![0; template.len()];
+        template.render(&mut buffer, &mut thread_rng());
+
+        assert_eq!(&buffer[0..4], &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(&buffer[4..6], &1234u16.to_be_bytes());
+        assert_eq!(&buffer[6..10], &1u32.to_le_bytes());
+        assert_eq!(&buffer[14..16], &(template.len() as u16).to_be_bytes());
+    }
+
+    #[test]
+    fn renders_distinct_random_spans() {
+        let template = PacketTemplate::parse("random(32)").expect("Must parse a valid template");
+
+        let mut first = vec![0; template.len()];
+        let mut second = vec![0; template.len()];
+        template.render(&mut first, &mut thread_rng());
+        template.render(&mut second, &mut thread_rng());
+
+        // Vanishingly unlikely to collide for 32 random bytes, so this also
+        // verifies that every call re-randomizes the span.
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn rejects_unknown_field_keyword() {
+        assert!(PacketTemplate::parse("nonsense").is_err());
+    }
+
+    #[test]
+    fn rejects_integer_field_out_of_range() {
+        match PacketTemplate::parse("u8be 300") {
+            Err(TemplateError::InvalidLine(1, FieldParseError::ValueOutOfRange(300, 1))) => {}
+            other => panic!("Must reject an out-of-range integer field, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_length_field_too_narrow_for_the_packet() {
+        let template = format!("literal {}\nlength-u8be", "ab".repeat(256));
+
+        match PacketTemplate::parse(&template) {
+            Err(TemplateError::LengthOutOfRange(257, 1)) => {}
+            other => panic!(
+                "Must reject a length field that can't fit the packet size, got {:?}",
+                other
+            ),
+        }
+    }
 }
@@ -20,7 +20,7 @@ use std::error::Error;
 use std::fmt::{self, Display, Formatter};
 use std::io;
 
-use super::config::LoggingConfig;
+use super::config::{LogFormat, LoggingConfig};
 
 use colored::Colorize as _;
 use fern::colors::{Color, ColoredLevelConfig};
@@ -31,41 +31,60 @@ use time::{self, ParseError};
 pub fn setup_logging(logging_config: &LoggingConfig) -> Result<(), SetupLoggingError> {
     check_time_format(&logging_config.date_time_format).map_err(SetupLoggingError::InvalidFormatError)?;
 
-    let colors = ColoredLevelConfig::new()
-        .info(Color::Green)
-        .warn(Color::Yellow)
-        .error(Color::Red)
-        .debug(Color::Magenta)
-        .trace(Color::Cyan);
+    let user_facing_filter = |metadata: &log::Metadata| match metadata.level() {
+        Level::Info | Level::Warn | Level::Error => true,
+        Level::Debug | Level::Trace => false,
+    };
 
-    let date_time_format = logging_config.date_time_format.clone();
+    // In the JSON mode every significant event is already rendered as a
+    // complete JSON object by its caller, so we bypass the colored
+    // formatter entirely and print the message as-is to keep the stream
+    // machine-parseable. A message that its caller didn't render as JSON
+    // (such as the start-up waiting notice) is printed as-is too, as plain
+    // text.
+    let mut dispatch = match logging_config.log_format {
+        LogFormat::Json => Dispatch::new()
+            .format(|out, message, _record| out.finish(format_args!("{}", message)))
+            .chain(
+                Dispatch::new()
+                    .filter(user_facing_filter)
+                    .chain(io::stdout()),
+            ),
+        LogFormat::Text => {
+            let colors = ColoredLevelConfig::new()
+                .info(Color::Green)
+                .warn(Color::Yellow)
+                .error(Color::Red)
+                .debug(Color::Magenta)
+                .trace(Color::Cyan);
+
+            let date_time_format = logging_config.date_time_format.clone();
 
-    let mut dispatch = Dispatch::new()
-        // Print fancy colored output to a terminal without a record date
-        // and the program name
-        .format(move |out, message, record| {
-            out.finish(format_args!(
-                "[{level}] [{time}]: {message}",
-                level = colors.color(record.level()).to_string().underline(),
-                time = time::strftime(&date_time_format, &time::now())
-                    // Now we can unwrap the result because we know that the specified time format
-                    // is correct
-                    .unwrap()
-                    .magenta(),
-                message = message,
-            ));
-        })
-        // Anyway, print all user-oriented information (notifications, warnings,
-        // and errors) to stdout
-        .chain(
             Dispatch::new()
-                .filter(move |metadata| match metadata.level() {
-                    Level::Info | Level::Warn | Level::Error => true,
-                    Level::Debug | Level::Trace => false,
+                // Print fancy colored output to a terminal without a record date
+                // and the program name
+                .format(move |out, message, record| {
+                    out.finish(format_args!(
+                        "[{level}] [{time}]: {message}",
+                        level = colors.color(record.level()).to_string().underline(),
+                        time = time::strftime(&date_time_format, &time::now())
+                            // Now we can unwrap the result because we know that the specified time format
+                            // is correct
+                            .unwrap()
+                            .magenta(),
+                        message = message,
+                    ));
                 })
-                .chain(io::stdout()),
-        )
-        .level(associated_level(logging_config.verbosity));
+                // Anyway, print all user-oriented information (notifications, warnings,
+                // and errors) to stdout
+                .chain(
+                    Dispatch::new()
+                        .filter(user_facing_filter)
+                        .chain(io::stdout()),
+                )
+        }
+    }
+    .level(associated_level(logging_config.verbosity));
 
     // If the debug mode is on, then allow printing all debugging messages and
     // traces
@@ -18,21 +18,22 @@
 
 use std::fmt::Display;
 use std::io::{self, IoVec};
-use std::net::UdpSocket;
+use std::net::{SocketAddr, UdpSocket};
+use std::os::unix::io::AsRawFd;
 use std::thread::{self, Builder, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anevicon_core::{self, TestSummary, Tester};
 use humantime::format_duration;
 use log::{error, info, warn};
+use rand::thread_rng;
 
-use super::config::{ArgsConfig, NetworkConfig};
-use super::helpers::{self, SummaryWrapper};
-use colored::ColoredString;
+use super::config::{ArgsConfig, LogFormat, NetworkConfig, RateConfig};
+use super::helpers::{self, PacketSource, SummaryWrapper};
 
 pub fn execute_testers(
     config: &'static ArgsConfig,
-    packet: &'static [u8],
+    packet: &'static PacketSource,
 ) -> io::Result<Vec<JoinHandle<()>>> {
     wait(config.wait);
 
@@ -41,39 +42,55 @@ pub fn execute_testers(
     let sendings_count = (config.exit_config.packets_count.get() - remaining_packets)
         / config.network_config.packets_per_syscall.get();
 
-    Ok(init_sockets(&config.network_config)?
+    let rate_limit = RateLimit::from_config(&config.rate_config);
+    let log_format = config.logging_config.log_format;
+
+    Ok(init_sockets(&config.network_config, log_format)?
         .into_iter()
         .enumerate()
         .map(|(i, socket)| {
+            let receiver = config.network_config.receivers[i];
+
             Builder::new()
-                .name(config.network_config.receivers[i].to_string())
+                .name(receiver.to_string())
                 .spawn(move || {
+                    let packets_per_syscall = config.network_config.packets_per_syscall.get();
                     let (mut ordinary, mut remaining) = (
-                        generate_portions(config.network_config.packets_per_syscall.get(), &packet),
-                        generate_portions(remaining_packets, &packet),
+                        PortionBuffers::new(packets_per_syscall, packet),
+                        PortionBuffers::new(remaining_packets, packet),
                     );
 
+                    let mut bucket = rate_limit
+                        .map(|limit| TokenBucket::new(limit, packets_per_syscall, packet.len()));
+
                     let mut summary = TestSummary::default();
                     let mut tester = Tester::new(&socket, &mut summary);
 
                     // Run the loop for the current worker until the allotted time expires or all
                     // the packets will have been sent
                     for _ in 0..sendings_count {
-                        if let Err(error) = tester.send_multiple(&mut ordinary) {
-                            send_multiple_error(error);
+                        ordinary.refresh(packet);
+
+                        if let Err(error) = tester.send_multiple(&mut ordinary.portions()) {
+                            send_multiple_error(log_format, receiver, error);
                         }
 
-                        display_summary(SummaryWrapper(tester.summary()));
+                        display_summary(log_format, receiver, tester.summary());
 
                         if tester.summary().time_passed() >= config.exit_config.test_duration {
-                            display_expired_time(SummaryWrapper(tester.summary()));
+                            display_expired_time(log_format, receiver, tester.summary());
                         }
 
-                        thread::sleep(config.send_periodicity);
+                        match bucket {
+                            Some(ref mut bucket) => bucket.throttle(packets_per_syscall),
+                            None => thread::sleep(config.send_periodicity),
+                        }
                     }
 
-                    if let Err(error) = tester.send_multiple(&mut remaining) {
-                        send_multiple_error(error);
+                    remaining.refresh(packet);
+
+                    if let Err(error) = tester.send_multiple(&mut remaining.portions()) {
+                        send_multiple_error(log_format, receiver, error);
                     }
 
                     // We might have a situation when not all the required packets are sent, so fix
@@ -82,9 +99,9 @@ pub fn execute_testers(
                         tester.summary().packets_expected() - tester.summary().packets_sent();
 
                     if unsent != 0 {
-                        resend_packets(&mut tester, &packet, unsent);
+                        resend_packets(log_format, receiver, &mut tester, packet, unsent);
                     } else {
-                        display_packets_sent(SummaryWrapper(tester.summary()));
+                        display_packets_sent(log_format, receiver, tester.summary());
                     }
                 })
                 .expect("Unable to spawn a new thread")
@@ -92,6 +109,93 @@ pub fn execute_testers(
         .collect())
 }
 
+/// A configured target rate, either a packet rate or a bandwidth, that a
+/// `TokenBucket` enforces for a single worker thread.
+#[derive(Debug, Clone, Copy)]
+enum RateLimit {
+    PacketsPerSec(f64),
+    BitsPerSec(f64),
+}
+
+impl RateLimit {
+    fn from_config(config: &RateConfig) -> Option<RateLimit> {
+        if let Some(rate) = config.rate {
+            Some(RateLimit::PacketsPerSec(rate.get() as f64))
+        } else {
+            config
+                .bandwidth
+                .map(|bandwidth| RateLimit::BitsPerSec(bandwidth.get() as f64))
+        }
+    }
+}
+
+// A token-bucket limiter that paces a single worker thread to a configured
+// packet rate or bandwidth, replacing the coarse `--send-periodicity` sleep
+// with an accurate sustained-rate delay between syscall batches.
+struct TokenBucket {
+    limit: RateLimit,
+    packet_bits: f64,
+    tokens: f64,
+    burst: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, packets_per_syscall: usize, packet_len: usize) -> TokenBucket {
+        let packet_bits = (packet_len * 8) as f64;
+        let burst = match limit {
+            RateLimit::PacketsPerSec(_) => packets_per_syscall as f64,
+            RateLimit::BitsPerSec(_) => packets_per_syscall as f64 * packet_bits,
+        };
+
+        TokenBucket {
+            limit,
+            packet_bits,
+            tokens: burst,
+            burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    // Refills the bucket for the elapsed time, withdraws the cost of sending
+    // `packets` packets, and sleeps off any resulting deficit.
+    fn throttle(&mut self, packets: usize) {
+        let rate = match self.limit {
+            RateLimit::PacketsPerSec(rate) => rate,
+            RateLimit::BitsPerSec(rate) => rate,
+        };
+
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last_refill).as_secs_f64() * rate)
+            .min(self.burst);
+        self.last_refill = now;
+
+        self.tokens -= match self.limit {
+            RateLimit::PacketsPerSec(_) => packets as f64,
+            RateLimit::BitsPerSec(_) => packets as f64 * self.packet_bits,
+        };
+
+        if self.tokens < 0.0 {
+            precise_sleep(Duration::from_secs_f64(-self.tokens / rate));
+        }
+    }
+}
+
+// Sleeps for the bulk of `duration` and busy-spins the final sub-millisecond
+// to correct for the OS scheduler's sleep granularity.
+fn precise_sleep(duration: Duration) {
+    const SPIN_THRESHOLD: Duration = Duration::from_millis(1);
+    let deadline = Instant::now() + duration;
+
+    if duration > SPIN_THRESHOLD {
+        thread::sleep(duration - SPIN_THRESHOLD);
+    }
+
+    while Instant::now() < deadline {
+        thread::yield_now();
+    }
+}
+
 fn wait(duration: Duration) {
     warn!(
         "Waiting {time} and then starting to initialize the sockets...",
@@ -100,74 +204,132 @@ fn wait(duration: Duration) {
     thread::sleep(duration);
 }
 
-fn resend_packets(tester: &mut Tester, packet: &[u8], count: usize) {
-    info!(
-        "Trying to resend {count} packets to the {receiver} that weren't sent...",
-        count = count,
-        receiver = current_receiver()
-    );
+fn resend_packets(
+    log_format: LogFormat,
+    receiver: SocketAddr,
+    tester: &mut Tester,
+    packet: &PacketSource,
+    count: usize,
+) {
+    match log_format {
+        LogFormat::Text => info!(
+            "Trying to resend {count} packets to the {receiver} that weren't sent...",
+            count = count,
+            receiver = helpers::cyan(receiver)
+        ),
+        LogFormat::Json => info!(
+            "{}",
+            helpers::json_message_event(
+                receiver,
+                "resend",
+                format!("resending {} unsent packets", count)
+            )
+        ),
+    }
+
+    let mut buffer = vec![0; packet.len()];
 
     for _ in 0..count {
+        packet.render(&mut buffer, &mut thread_rng());
+
         loop {
-            if let Err(error) = tester.send_once(IoVec::new(packet)) {
-                error!(
-                    "An error occurred while sending a packet to the {receiver} >>> {error}! \
-                     Retrying the operation...",
-                    receiver = current_receiver(),
-                    error = error
-                );
+            if let Err(error) = tester.send_once(IoVec::new(&buffer)) {
+                match log_format {
+                    LogFormat::Text => error!(
+                        "An error occurred while sending a packet to the {receiver} >>> {error}! \
+                         Retrying the operation...",
+                        receiver = helpers::cyan(receiver),
+                        error = error
+                    ),
+                    LogFormat::Json => {
+                        error!(
+                            "{}",
+                            helpers::json_message_event(receiver, "send_error", error)
+                        )
+                    }
+                }
             } else {
                 break;
             }
         }
     }
 
-    info!(
-        "{count} packets were successfully resent to the {receiver}.",
-        count = count,
-        receiver = current_receiver()
-    );
-}
-
-fn display_expired_time(summary: SummaryWrapper) {
-    info!(
-        "The allotted time has passed for the {receiver} >>> {summary}.",
-        receiver = current_receiver(),
-        summary = summary,
-    );
+    match log_format {
+        LogFormat::Text => info!(
+            "{count} packets were successfully resent to the {receiver}.",
+            count = count,
+            receiver = helpers::cyan(receiver)
+        ),
+        LogFormat::Json => info!(
+            "{}",
+            helpers::json_message_event(
+                receiver,
+                "resend",
+                format!("{} packets were successfully resent", count)
+            )
+        ),
+    }
 }
 
-fn display_packets_sent(summary: SummaryWrapper) {
-    info!(
-        "All the packets were sent for the {receiver} >>> {summary}",
-        receiver = current_receiver(),
-        summary = summary
-    );
+fn display_expired_time(log_format: LogFormat, receiver: SocketAddr, summary: &TestSummary) {
+    match log_format {
+        LogFormat::Text => info!(
+            "The allotted time has passed for the {receiver} >>> {summary}.",
+            receiver = helpers::cyan(receiver),
+            summary = SummaryWrapper(summary),
+        ),
+        LogFormat::Json => info!(
+            "{}",
+            helpers::json_summary_event(receiver, "expired_time", summary)
+        ),
+    }
 }
 
-fn display_summary(summary: SummaryWrapper) {
-    info!(
-        "Stats for the {receiver} >>> {summary}.",
-        receiver = current_receiver(),
-        summary = summary,
-    );
+fn display_packets_sent(log_format: LogFormat, receiver: SocketAddr, summary: &TestSummary) {
+    match log_format {
+        LogFormat::Text => info!(
+            "All the packets were sent for the {receiver} >>> {summary}",
+            receiver = helpers::cyan(receiver),
+            summary = SummaryWrapper(summary)
+        ),
+        LogFormat::Json => info!(
+            "{}",
+            helpers::json_summary_event(receiver, "completion", summary)
+        ),
+    }
 }
 
-fn send_multiple_error<E: Display>(error: E) {
-    error!(
-        "An error occurred while sending packets to the {receiver} >>> {error}!",
-        receiver = current_receiver(),
-        error = error
-    );
+fn display_summary(log_format: LogFormat, receiver: SocketAddr, summary: &TestSummary) {
+    match log_format {
+        LogFormat::Text => info!(
+            "Stats for the {receiver} >>> {summary}.",
+            receiver = helpers::cyan(receiver),
+            summary = SummaryWrapper(summary),
+        ),
+        LogFormat::Json => info!(
+            "{}",
+            helpers::json_summary_event(receiver, "summary", summary)
+        ),
+    }
 }
 
-// Extracts the current receiver from the current thread name and colorizes it
-// as cyan
-fn current_receiver() -> ColoredString {
-    helpers::cyan(thread::current().name().unwrap())
+fn send_multiple_error<E: Display>(log_format: LogFormat, receiver: SocketAddr, error: E) {
+    match log_format {
+        LogFormat::Text => error!(
+            "An error occurred while sending packets to the {receiver} >>> {error}!",
+            receiver = helpers::cyan(receiver),
+            error = error
+        ),
+        LogFormat::Json => {
+            error!(
+                "{}",
+                helpers::json_message_event(receiver, "send_error", error)
+            )
+        }
+    }
 }
 
-fn init_sockets(config: &NetworkConfig) -> io::Result<Vec<UdpSocket>> {
+fn init_sockets(config: &NetworkConfig, log_format: LogFormat) -> io::Result<Vec<UdpSocket>> {
     let mut sockets = Vec::with_capacity(config.receivers.len());
 
     for receiver in config.receivers.iter() {
@@ -176,10 +338,20 @@ fn init_sockets(config: &NetworkConfig) -> io::Result<Vec<UdpSocket>> {
         socket.set_broadcast(config.broadcast)?;
         socket.set_write_timeout(Some(config.send_timeout))?;
 
-        info!(
-            "A new socket was initialized to the {receiver} receiver...",
-            receiver = helpers::cyan(receiver),
-        );
+        if let Some(tos) = config.ip_tos {
+            set_ip_tos(&socket, *receiver, tos)?;
+        }
+
+        match log_format {
+            LogFormat::Text => info!(
+                "A new socket was initialized to the {receiver} receiver...",
+                receiver = helpers::cyan(receiver),
+            ),
+            LogFormat::Json => info!(
+                "{}",
+                helpers::json_message_event(*receiver, "socket_init", "socket initialized")
+            ),
+        }
 
         sockets.push(socket);
     }
@@ -187,12 +359,79 @@ fn init_sockets(config: &NetworkConfig) -> io::Result<Vec<UdpSocket>> {
     Ok(sockets)
 }
 
-fn generate_portions(length: usize, packet: &[u8]) -> Vec<(usize, IoVec)> {
-    let mut portions = Vec::with_capacity(length);
+// Applies the ToS/DSCP+ECN octet to a freshly-connected socket, picking the
+// IPv4 or IPv6 sockopt depending on the receiver's address family.
+fn set_ip_tos(socket: &UdpSocket, receiver: SocketAddr, tos: u8) -> io::Result<()> {
+    let fd = socket.as_raw_fd();
+    let value = tos as libc::c_int;
+
+    let result = match receiver {
+        SocketAddr::V4(_) => unsafe {
+            libc::setsockopt(
+                fd,
+                libc::IPPROTO_IP,
+                libc::IP_TOS,
+                &value as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        },
+        SocketAddr::V6(_) => unsafe {
+            libc::setsockopt(
+                fd,
+                libc::IPPROTO_IPV6,
+                libc::IPV6_TCLASS,
+                &value as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        },
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+// Owns the fixed-size batch of per-portion buffers a single syscall sends.
+// A `Fixed` packet shares one `'static` buffer across every portion, so
+// there is nothing to refresh between sends. A `Templated` one owns a
+// distinct buffer per portion that `refresh` re-renders — re-randomizing
+// every `random` span and recomputing length fields — before every send, so
+// each datagram in the batch can carry fresh content instead of reusing
+// whatever was rendered when the thread started.
+enum PortionBuffers {
+    Fixed(&'static [u8], usize),
+    Templated(Vec<Vec<u8>>),
+}
+
+impl PortionBuffers {
+    fn new(length: usize, packet: &'static PacketSource) -> PortionBuffers {
+        match packet {
+            PacketSource::Fixed(bytes) => PortionBuffers::Fixed(bytes.as_slice(), length),
+            PacketSource::Templated(_) => {
+                PortionBuffers::Templated(vec![vec![0; packet.len()]; length])
+            }
+        }
+    }
 
-    for _ in 0..length {
-        portions.push((0, IoVec::new(packet)));
+    fn refresh(&mut self, packet: &PacketSource) {
+        if let PortionBuffers::Templated(buffers) = self {
+            for buffer in buffers {
+                packet.render(buffer, &mut thread_rng());
+            }
+        }
     }
 
-    portions
-}
\ No newline at end of file
+    fn portions(&self) -> Vec<(usize, IoVec)> {
+        match self {
+            PortionBuffers::Fixed(bytes, length) => {
+                (0..*length).map(|_| (0, IoVec::new(bytes))).collect()
+            }
+            PortionBuffers::Templated(buffers) => buffers
+                .iter()
+                .map(|buffer| (0, IoVec::new(buffer)))
+                .collect(),
+        }
+    }
+}